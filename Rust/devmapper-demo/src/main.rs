@@ -1,9 +1,27 @@
 use anyhow::{Context, Result};
+use quick_xml::events::{BytesStart, Event};
+use quick_xml::reader::Reader;
 use std::{
     path::{Path, PathBuf},
     process::Command,
+    thread::{self, JoinHandle},
+    time::Duration,
 };
 
+/// Selects how `LoopDevice`, `ThinPool`, and `ThinVolume` talk to the kernel.
+/// `Shell` drives the same dmsetup/losetup/blockdev CLIs the original demo
+/// used; `Ioctl` issues `DM_IOCTL`s and loop ioctls directly against
+/// `/dev/mapper/control` and `/dev/loop-control`, avoiding a process spawn
+/// and stdout-parsing per operation (what dmsetup itself does under the
+/// hood). Kept as an enum rather than always switching over so the shell
+/// path stays available for tracing and for environments without direct
+/// ioctl access.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+enum Backend {
+    Shell,
+    Ioctl,
+}
+
 /// Represents a loop device, presenting a file as a block device.
 struct LoopDevice {
     /// Path to the loop device in /dev
@@ -12,13 +30,17 @@ struct LoopDevice {
     /// Path to the backing file
     backing_file: PathBuf,
 
+    /// Which backend was used to attach this device, so `detach` tears it
+    /// down the same way.
+    backend: Backend,
+
     /// Whether this loop device should be detached when dropped
     should_cleanup: bool,
 }
 
 impl LoopDevice {
     /// Create a backing sparse file and attaches it to a loop device.
-    fn create(backing_file: PathBuf, size_mb: u64) -> Result<Self> {
+    fn create(backing_file: PathBuf, size_mb: u64, backend: Backend) -> Result<Self> {
         println!("Creating backing file: {:?} ({}MB)", backing_file, size_mb);
 
         let size_bytes = size_mb * 1024 * 1024;
@@ -40,6 +62,36 @@ impl LoopDevice {
 
         println!("Backing file allocated");
 
+        let device_path = match backend {
+            Backend::Shell => Self::attach_shell(&backing_file)?,
+            Backend::Ioctl => dm::loop_attach(&backing_file)?,
+        };
+
+        println!("Loop device attached: {}", device_path.display());
+
+        // Verify the size
+        let reported_size = Self::size_bytes(&device_path, backend)?;
+        println!("Loop device size: {} bytes", reported_size);
+
+        if reported_size != size_bytes {
+            anyhow::bail!(
+                "Loop device has wrong size: {} (expected {})",
+                reported_size,
+                size_bytes
+            );
+        }
+
+        Ok(Self {
+            device_path,
+            backing_file,
+            backend,
+            should_cleanup: true,
+        })
+    }
+
+    /// Finds a free loop device via `losetup -f` and attaches the backing
+    /// file to it via `losetup <dev> <file>`.
+    fn attach_shell(backing_file: &Path) -> Result<PathBuf> {
         // Find a free loop device
         let output = Command::new("losetup")
             .arg("-f") // Just find, don't attach yet
@@ -60,7 +112,7 @@ impl LoopDevice {
         // Now explicitly attach the file to that device
         let status = Command::new("losetup")
             .arg(&device_path)
-            .arg(&backing_file)
+            .arg(backing_file)
             .status()
             .context("Failed to attach loop device")?;
 
@@ -68,52 +120,54 @@ impl LoopDevice {
             anyhow::bail!("Failed to attach loop device");
         }
 
-        println!("Loop device attached: {}", device_path);
-
-        // Verify the size
-        let size_check = Command::new("blockdev")
-            .arg("--getsize64")
-            .arg(&device_path)
-            .output()?;
-
-        let reported_size = String::from_utf8(size_check.stdout)?
-            .trim()
-            .parse::<u64>()?;
-
-        println!("Loop device size: {} bytes", reported_size);
+        Ok(PathBuf::from(device_path))
+    }
 
-        if reported_size != size_bytes {
-            anyhow::bail!(
-                "Loop device has wrong size: {} (expected {})",
-                reported_size,
-                size_bytes
-            );
+    /// Returns the device's reported size in bytes via whichever backend
+    /// attached it.
+    fn size_bytes(device_path: &Path, backend: Backend) -> Result<u64> {
+        match backend {
+            Backend::Shell => {
+                let size_check = Command::new("blockdev")
+                    .arg("--getsize64")
+                    .arg(device_path)
+                    .output()?;
+
+                let reported_size = String::from_utf8(size_check.stdout)?
+                    .trim()
+                    .parse::<u64>()?;
+                Ok(reported_size)
+            }
+            Backend::Ioctl => dm::block_device_size_bytes(device_path),
         }
-
-        Ok(Self {
-            device_path: PathBuf::from(device_path),
-            backing_file,
-            should_cleanup: true,
-        })
     }
 
     fn device_path(&self) -> &Path {
         &self.device_path
     }
 
+    fn backing_file(&self) -> &Path {
+        &self.backing_file
+    }
+
     // Detaches the loop device from its backing file.
     fn detach(&mut self) -> Result<()> {
         if !self.should_cleanup {
             return Ok(());
         }
 
-        let status = Command::new("losetup")
-            .arg("-d")
-            .arg(&self.device_path)
-            .status()
-            .context("Failed to execute losetup -d")?;
-        if !status.success() {
-            anyhow::bail!("Failed to detach loop device: {:?}", self.device_path);
+        match self.backend {
+            Backend::Shell => {
+                let status = Command::new("losetup")
+                    .arg("-d")
+                    .arg(&self.device_path)
+                    .status()
+                    .context("Failed to execute losetup -d")?;
+                if !status.success() {
+                    anyhow::bail!("Failed to detach loop device: {:?}", self.device_path);
+                }
+            }
+            Backend::Ioctl => dm::loop_detach(&self.device_path)?,
         }
 
         self.should_cleanup = false;
@@ -143,10 +197,177 @@ struct ThinPool {
     /// Size of data chunks in the pool (in 512-byte sectors)
     data_block_size: u64,
 
+    /// Which backend was used to create this pool, so `create_thin_volume`
+    /// and `remove` operate on it the same way.
+    backend: Backend,
+
+    /// Low water mark (in data blocks free) at which the kernel raises a dm
+    /// event, threaded through so `monitor` reloads the table with the same
+    /// value after growing the data device.
+    low_water_mark_blocks: u64,
+
     /// Whether this pool should be cleaned up when dropped
     should_cleanup: bool,
 }
 
+/// Configures `ThinPool::monitor`'s low-water-mark polling and automatic
+/// data/metadata device extension.
+#[derive(Clone, Copy, Debug)]
+struct PoolThresholds {
+    /// Grow the data device once used/total data blocks crosses this
+    /// fraction (0.0-1.0).
+    data_high_water: f64,
+
+    /// Grow the metadata device once used/total metadata blocks crosses
+    /// this fraction (0.0-1.0).
+    metadata_high_water: f64,
+
+    /// How much to grow a backing file by, in MB, each time it crosses its
+    /// threshold.
+    grow_by_mb: u64,
+
+    /// How often to poll the pool's status.
+    poll_interval: Duration,
+}
+
+/// How `ThinPool::create` should populate a freshly-attached metadata
+/// device before the pool is activated on it.
+enum MetadataInit {
+    /// Write a blank thin-pool superblock via `thin_restore`.
+    Empty,
+
+    /// Reuse a pre-existing metadata image. `thin_check` runs against it
+    /// first; if it finds recoverable damage and `repair_if_needed` is set,
+    /// `thin_repair` dumps and restores it into a fresh metadata device
+    /// before activation. Unrecoverable or unrequested repairs are an error.
+    Reuse {
+        metadata_image: PathBuf,
+        repair_if_needed: bool,
+    },
+}
+
+/// Structured result of a `thin_check` metadata integrity pass: whether the
+/// superblock, device and mapping b-trees, space maps, and reference counts
+/// came back clean, and whether the damage (if any) looks like something
+/// `thin_repair`'s dump-and-restore cycle can fix.
+#[derive(Debug, Clone)]
+struct ThinCheckReport {
+    errors_found: bool,
+    repair_recommended: bool,
+    output: String,
+}
+
+/// Grows a loop-backed device in place: extends the backing file by
+/// `additional_mb` via `fallocate`, then updates the loop device's reported
+/// capacity to match via `losetup --set-capacity` or `LOOP_SET_CAPACITY`.
+fn grow_backing_device(
+    backing_file: &Path,
+    device_path: &Path,
+    additional_mb: u64,
+    backend: Backend,
+) -> Result<()> {
+    let current_bytes = LoopDevice::size_bytes(device_path, backend)?;
+    let new_bytes = current_bytes + additional_mb * 1024 * 1024;
+
+    let status = Command::new("fallocate")
+        .arg("-l")
+        .arg(format!("{}", new_bytes))
+        .arg(backing_file)
+        .status()
+        .context("Failed to run fallocate")?;
+    if !status.success() {
+        anyhow::bail!("fallocate failed while growing backing file: {:?}", backing_file);
+    }
+
+    match backend {
+        Backend::Shell => {
+            let status = Command::new("losetup")
+                .arg("--set-capacity")
+                .arg(device_path)
+                .status()
+                .context("Failed to run losetup --set-capacity")?;
+            if !status.success() {
+                anyhow::bail!("losetup --set-capacity failed for {:?}", device_path);
+            }
+        }
+        Backend::Ioctl => dm::loop_set_capacity(device_path)?,
+    }
+
+    Ok(())
+}
+
+/// Reloads a device's table with a new target line and atomically swaps it
+/// in via suspend/resume, for growing a pool's data or metadata device in
+/// place without tearing the device down. Via `dmsetup suspend`/`load`/
+/// `resume` or `DM_DEV_SUSPEND` + `DM_TABLE_LOAD` + `DM_DEV_SUSPEND`
+/// (resume).
+fn dm_reload_table(name: &str, table: &str, backend: Backend) -> Result<()> {
+    match backend {
+        Backend::Shell => {
+            let status = Command::new("dmsetup")
+                .arg("suspend")
+                .arg(name)
+                .status()
+                .context("Failed to execute dmsetup suspend")?;
+            if !status.success() {
+                anyhow::bail!("dmsetup suspend failed for device: {}", name);
+            }
+
+            let status = Command::new("dmsetup")
+                .arg("load")
+                .arg(name)
+                .arg("--table")
+                .arg(table)
+                .status()
+                .context("Failed to execute dmsetup load")?;
+            if !status.success() {
+                anyhow::bail!("dmsetup load failed for device: {}", name);
+            }
+
+            let status = Command::new("dmsetup")
+                .arg("resume")
+                .arg(name)
+                .status()
+                .context("Failed to execute dmsetup resume")?;
+            if !status.success() {
+                anyhow::bail!("dmsetup resume failed for device: {}", name);
+            }
+            Ok(())
+        }
+        Backend::Ioctl => {
+            let dm = dm::Dm::open()?;
+            dm.dev_suspend(name, true)
+                .with_context(|| format!("DM_DEV_SUSPEND (suspend) failed for device: {}", name))?;
+            dm.table_load(name, table)
+                .with_context(|| format!("DM_TABLE_LOAD failed for device: {}", name))?;
+            dm.dev_suspend(name, false)
+                .with_context(|| format!("DM_DEV_SUSPEND (resume) failed for device: {}", name))
+        }
+    }
+}
+
+/// Builds a thin-pool table line: `<start> <len> thin-pool <metadata dev>
+/// <data dev> <data block size> <low water mark> 1 skip_block_zeroing`.
+/// Shared between initial pool creation and `ThinPool::monitor`'s table
+/// reload after growing the data device.
+fn pool_table_line(
+    metadata_path: &Path,
+    data_path: &Path,
+    data_block_size_sectors: u64,
+    low_water_mark_blocks: u64,
+    backend: Backend,
+) -> Result<String> {
+    let data_size_sectors = ThinPool::get_device_size_sectors(data_path, backend)?;
+    Ok(format!(
+        "0 {} thin-pool {} {} {} {} 1 skip_block_zeroing",
+        data_size_sectors,
+        metadata_path.display(),
+        data_path.display(),
+        data_block_size_sectors,
+        low_water_mark_blocks,
+    ))
+}
+
 impl ThinPool {
     /// Create a new thin pool with the specified configuration.
     fn create(
@@ -154,6 +375,9 @@ impl ThinPool {
         metadata_size_mb: u64,
         data_size_mb: u64,
         data_block_size_sectors: u64,
+        low_water_mark_blocks: u64,
+        metadata_init: MetadataInit,
+        backend: Backend,
     ) -> Result<Self> {
         let metadata_backing = PathBuf::from(format!("/tmp/{}-metadata.img", pool_name));
         let data_backing = PathBuf::from(format!("/tmp/{}-data.img", pool_name));
@@ -161,9 +385,9 @@ impl ThinPool {
         let _ = std::fs::remove_file(&metadata_backing);
         let _ = std::fs::remove_file(&data_backing);
 
-        let metadata_dev = LoopDevice::create(metadata_backing, metadata_size_mb)
+        let metadata_dev = LoopDevice::create(metadata_backing, metadata_size_mb, backend)
             .context("Failed to create metadata loop device")?;
-        let data_dev = LoopDevice::create(data_backing, data_size_mb)
+        let data_dev = LoopDevice::create(data_backing, data_size_mb, backend)
             .context("Failed to create data loop device")?;
         println!("\nLoop devices created:");
         println!("  Metadata: {:?}", metadata_dev.device_path());
@@ -171,17 +395,34 @@ impl ThinPool {
 
         // The metadata device needs to have a valid thin pool structure before
         // creating the pool device
-        Self::initialize_metadata(
-            metadata_dev.device_path(),
-            data_size_mb,
-            data_block_size_sectors,
-        )?;
+        match metadata_init {
+            MetadataInit::Empty => {
+                Self::initialize_metadata(
+                    metadata_dev.device_path(),
+                    data_size_mb,
+                    data_block_size_sectors,
+                )?;
+            }
+            MetadataInit::Reuse {
+                metadata_image,
+                repair_if_needed,
+            } => {
+                Self::load_existing_metadata(
+                    &pool_name,
+                    &metadata_image,
+                    metadata_dev.device_path(),
+                    repair_if_needed,
+                )?;
+            }
+        }
 
         Self::create_pool_device(
             &pool_name,
             metadata_dev.device_path(),
             data_dev.device_path(),
             data_block_size_sectors,
+            low_water_mark_blocks,
+            backend,
         )?;
 
         Ok(Self {
@@ -189,6 +430,8 @@ impl ThinPool {
             metadata_dev,
             data_dev,
             data_block_size: data_block_size_sectors,
+            backend,
+            low_water_mark_blocks,
             should_cleanup: true,
         })
     }
@@ -230,57 +473,134 @@ impl ThinPool {
         Ok(())
     }
 
-    /// Creates device mapper thin pool device.
-    fn create_pool_device(
+    /// Validates a pre-existing metadata image with `thin_check`, repairs it
+    /// via `thin_repair` if requested and recommended, then loads the result
+    /// onto `metadata_path` so the pool can be activated on it. Bails out
+    /// rather than activating a pool on metadata that's still damaged.
+    fn load_existing_metadata(
         pool_name: &str,
+        metadata_image: &Path,
         metadata_path: &Path,
-        data_path: &Path,
-        data_block_size_sectors: u64,
+        repair_if_needed: bool,
     ) -> Result<()> {
-        let data_size_sectors = Self::get_device_size_sectors(data_path)?;
-        // thin-pool table format
-        let table = format!(
-            "0 {} thin-pool {} {} {} 0 1 skip_block_zeroing",
-            data_size_sectors,
-            metadata_path.display(),
-            data_path.display(),
-            data_block_size_sectors,
+        let report = Self::check_metadata(metadata_image)?;
+
+        let source = if report.errors_found {
+            if !repair_if_needed || !report.repair_recommended {
+                anyhow::bail!(
+                    "Metadata at {:?} failed integrity check and repair was not requested or not recommended:\n{}",
+                    metadata_image,
+                    report.output
+                );
+            }
+
+            println!(
+                "thin_check found recoverable damage in {:?}; repairing via thin_repair",
+                metadata_image
+            );
+            let repaired_image = PathBuf::from(format!("/tmp/{}-metadata-repaired.img", pool_name));
+            Self::repair_metadata(metadata_image, &repaired_image)?;
+            repaired_image
+        } else {
+            metadata_image.to_path_buf()
+        };
+
+        std::fs::copy(&source, metadata_path).with_context(|| {
+            format!("Failed to load metadata from {:?} onto {:?}", source, metadata_path)
+        })?;
+
+        Ok(())
+    }
+
+    /// Runs `thin_check` against `metadata_path` without mutating it,
+    /// validating the superblock, the device and mapping b-trees, the space
+    /// maps, and reference counts, and returns a structured report instead
+    /// of just an exit code.
+    fn check_metadata(metadata_path: &Path) -> Result<ThinCheckReport> {
+        let output = Command::new("thin_check")
+            .arg(metadata_path)
+            .output()
+            .context("Failed to run thin_check")?;
+
+        let combined_output = format!(
+            "{}{}",
+            String::from_utf8_lossy(&output.stdout),
+            String::from_utf8_lossy(&output.stderr)
         );
 
-        let status = Command::new("dmsetup")
-            .arg("create")
-            .arg(pool_name)
-            .arg("--table")
-            .arg(&table)
-            .arg("--verifyudev")
+        // thin_check exits 0 when the metadata is clean and 1 when it found
+        // errors that thin_repair's dump-and-restore cycle can usually fix.
+        // Any other exit code means it couldn't assess the metadata at all
+        // (bad arguments, unreadable device), so repair isn't recommended.
+        Ok(ThinCheckReport {
+            errors_found: !output.status.success(),
+            repair_recommended: output.status.code() == Some(1),
+            output: combined_output,
+        })
+    }
+
+    /// Repairs a metadata image by dumping it to XML and restoring that XML
+    /// into `repaired_path`, the same dump-and-restore cycle `thin_repair`
+    /// performs internally.
+    fn repair_metadata(metadata_path: &Path, repaired_path: &Path) -> Result<()> {
+        let status = Command::new("thin_repair")
+            .arg("-i")
+            .arg(metadata_path)
+            .arg("-o")
+            .arg(repaired_path)
             .status()
-            .context("Failed to execute dmsetup create")?;
+            .context("Failed to run thin_repair")?;
+
         if !status.success() {
-            anyhow::bail!("dmsetup create failed for pool: {}", pool_name);
+            anyhow::bail!("thin_repair failed with status: {}", status);
         }
 
         Ok(())
     }
 
-    /// Returns the size of a block device in 512-byte sectors.
-    fn get_device_size_sectors(device_path: &Path) -> Result<u64> {
-        let output = Command::new("blockdev")
-            .arg("--getsz")
-            .arg(device_path)
-            .output()
-            .context("Failed to run blockdev --getsz")?;
+    /// Creates device mapper thin pool device.
+    fn create_pool_device(
+        pool_name: &str,
+        metadata_path: &Path,
+        data_path: &Path,
+        data_block_size_sectors: u64,
+        low_water_mark_blocks: u64,
+        backend: Backend,
+    ) -> Result<()> {
+        let table = pool_table_line(
+            metadata_path,
+            data_path,
+            data_block_size_sectors,
+            low_water_mark_blocks,
+            backend,
+        )?;
+        dm_create_device(pool_name, &table, backend)
+    }
 
-        if !output.status.success() {
-            anyhow::bail!("blockdev --getsz failed");
+    /// Returns the size of a block device in 512-byte sectors.
+    fn get_device_size_sectors(device_path: &Path, backend: Backend) -> Result<u64> {
+        match backend {
+            Backend::Shell => {
+                let output = Command::new("blockdev")
+                    .arg("--getsz")
+                    .arg(device_path)
+                    .output()
+                    .context("Failed to run blockdev --getsz")?;
+
+                if !output.status.success() {
+                    anyhow::bail!("blockdev --getsz failed");
+                }
+
+                let size_str = String::from_utf8(output.stdout)
+                    .context("blockdev output is not a valid UTF-8")?;
+                let size = size_str
+                    .trim()
+                    .parse::<u64>()
+                    .context("Failed to parse device size")?;
+                Ok(size)
+            }
+            Backend::Ioctl => Ok(dm::block_device_size_bytes(device_path)? / 512),
         }
-
-        let size_str =
-            String::from_utf8(output.stdout).context("blockdev output is not a valid UTF-8")?;
-        let size = size_str
-            .trim()
-            .parse::<u64>()
-            .context("Failed to parse device size")?;
-        Ok(size)
     }
 
     /// Creates a thin volume from this pool.
@@ -290,17 +610,8 @@ impl ThinPool {
         virtual_size_mb: u64,
         device_id: u32,
     ) -> Result<ThinVolume> {
-        let message = format!("create_thin {}", device_id);
-        let status = Command::new("dmsetup")
-            .arg("message")
-            .arg(&format!("/dev/mapper/{}", self.name))
-            .arg("0") // Message to sector 0
-            .arg(&message)
-            .status()
-            .context("Failed to create_thin message")?;
-        if !status.success() {
-            anyhow::bail!("Failed to create thin volume with ID {}", device_id);
-        }
+        dm_message(&self.name, &format!("create_thin {}", device_id), self.backend)
+            .with_context(|| format!("Failed to create thin volume with ID {}", device_id))?;
 
         // thin volume table format
         let virtual_size_sectors = virtual_size_mb * 1024 * 1024 / 512;
@@ -308,23 +619,52 @@ impl ThinPool {
             "0 {} thin /dev/mapper/{} {}",
             virtual_size_sectors, self.name, device_id
         );
-        let status = Command::new("dmsetup")
-            .arg("create")
-            .arg(volume_name)
-            .arg("--table")
-            .arg(&table)
-            .arg("--verifyudev")
-            .status()
-            .context("Failed to create thin volume device")?;
-        if !status.success() {
-            anyhow::bail!("dmsetup create failed for volume: {}", volume_name);
-        }
+        dm_create_device(volume_name, &table, self.backend)?;
+
+        Ok(ThinVolume {
+            name: volume_name.to_string(),
+            pool_name: self.name.clone(),
+            device_id,
+            virtual_size_sectors,
+            backend: self.backend,
+            should_cleanup: true,
+        })
+    }
+
+    /// Creates a thin volume backed by an external, read-only origin device
+    /// (e.g. a shared base OS image on another loop device) instead of
+    /// starting out empty. Reads fall through to `external_origin` for any
+    /// block the volume hasn't written yet; every write goes copy-on-write
+    /// into the pool, so the origin is never modified. The volume's virtual
+    /// size defaults to the origin's size, since the external-origin target
+    /// presents the same address space as the device it's layered over.
+    fn create_thin_volume_with_external_origin(
+        &self,
+        volume_name: &str,
+        external_origin: &Path,
+        device_id: u32,
+    ) -> Result<ThinVolume> {
+        dm_message(&self.name, &format!("create_thin {}", device_id), self.backend)
+            .with_context(|| format!("Failed to create thin volume with ID {}", device_id))?;
+
+        let virtual_size_sectors =
+            Self::get_device_size_sectors(external_origin, self.backend)
+                .context("Failed to determine external origin size")?;
+        let table = format!(
+            "0 {} thin /dev/mapper/{} {} {}",
+            virtual_size_sectors,
+            self.name,
+            device_id,
+            external_origin.display()
+        );
+        dm_create_device(volume_name, &table, self.backend)?;
 
         Ok(ThinVolume {
             name: volume_name.to_string(),
             pool_name: self.name.clone(),
             device_id,
-            virtual_size_mb,
+            virtual_size_sectors,
+            backend: self.backend,
             should_cleanup: true,
         })
     }
@@ -335,18 +675,161 @@ impl ThinPool {
             return Ok(());
         }
 
-        let status = Command::new("dmsetup")
-            .arg("remove")
-            .arg(&self.name)
-            .status()
-            .context("Failed to remove thin pool")?;
-        if !status.success() {
-            anyhow::bail!("Failed to remove pool: {}", self.name);
-        }
+        dm_remove_device(&self.name, self.backend)
+            .with_context(|| format!("Failed to remove pool: {}", self.name))?;
 
         self.should_cleanup = false;
         Ok(())
     }
+
+    /// Queries and parses the pool's current `dmsetup status` line, giving
+    /// programmatic access to fill levels instead of scraping printed text.
+    fn status(&self) -> Result<ThinPoolStatus> {
+        let status_line = dm_status(&self.name, self.backend)
+            .with_context(|| format!("Failed to query status for pool: {}", self.name))?;
+        ThinPoolStatus::parse(&status_line)
+    }
+
+    /// Spawns a background thread that polls `status()` every
+    /// `thresholds.poll_interval` and, once used/total data or metadata
+    /// blocks crosses the configured high-water fraction, grows the
+    /// corresponding backing file and loop device by `thresholds.grow_by_mb`
+    /// and reloads the pool's table via suspend/resume, before the pool is
+    /// forced into out-of-data-space or needs_check. Runs until the process
+    /// exits; errors for a single poll are logged and the loop continues,
+    /// since a transient status query failure shouldn't kill monitoring.
+    fn monitor(&self, thresholds: PoolThresholds) -> JoinHandle<()> {
+        let pool_name = self.name.clone();
+        let backend = self.backend;
+        let data_block_size = self.data_block_size;
+        let low_water_mark_blocks = self.low_water_mark_blocks;
+        let metadata_backing = self.metadata_dev.backing_file().to_path_buf();
+        let metadata_device = self.metadata_dev.device_path().to_path_buf();
+        let data_backing = self.data_dev.backing_file().to_path_buf();
+        let data_device = self.data_dev.device_path().to_path_buf();
+
+        thread::spawn(move || loop {
+            thread::sleep(thresholds.poll_interval);
+
+            let status = match dm_status(&pool_name, backend)
+                .and_then(|line| ThinPoolStatus::parse(&line))
+            {
+                Ok(status) => status,
+                Err(err) => {
+                    eprintln!("pool monitor: failed to query status for {}: {}", pool_name, err);
+                    continue;
+                }
+            };
+
+            let data_fraction =
+                status.used_data_blocks as f64 / status.total_data_blocks as f64;
+            if data_fraction >= thresholds.data_high_water {
+                println!(
+                    "pool monitor: data usage at {:.1}%, growing data device by {}MB",
+                    data_fraction * 100.0,
+                    thresholds.grow_by_mb
+                );
+                let grown = grow_backing_device(&data_backing, &data_device, thresholds.grow_by_mb, backend)
+                    .and_then(|_| {
+                        pool_table_line(
+                            &metadata_device,
+                            &data_device,
+                            data_block_size,
+                            low_water_mark_blocks,
+                            backend,
+                        )
+                    })
+                    .and_then(|table| dm_reload_table(&pool_name, &table, backend));
+                if let Err(err) = grown {
+                    eprintln!("pool monitor: failed to grow data device: {}", err);
+                }
+            }
+
+            let metadata_fraction =
+                status.used_metadata_blocks as f64 / status.total_metadata_blocks as f64;
+            if metadata_fraction >= thresholds.metadata_high_water {
+                println!(
+                    "pool monitor: metadata usage at {:.1}%, growing metadata device by {}MB",
+                    metadata_fraction * 100.0,
+                    thresholds.grow_by_mb
+                );
+                let grown =
+                    grow_backing_device(&metadata_backing, &metadata_device, thresholds.grow_by_mb, backend)
+                        .and_then(|_| {
+                            pool_table_line(
+                                &metadata_device,
+                                &data_device,
+                                data_block_size,
+                                low_water_mark_blocks,
+                                backend,
+                            )
+                        })
+                        .and_then(|table| dm_reload_table(&pool_name, &table, backend));
+                if let Err(err) = grown {
+                    eprintln!("pool monitor: failed to grow metadata device: {}", err);
+                }
+            }
+        })
+    }
+
+    /// Takes a consistent, point-in-time snapshot of the pool's metadata
+    /// while the pool stays online and parses it into a structured
+    /// `ThinMetadata` report. The metadata snapshot is reserved via
+    /// `reserve_metadata_snap` and is always released again, even if
+    /// `thin_dump` fails, since device-mapper only allows one such snapshot
+    /// to be held at a time and a leaked reservation would wedge every
+    /// future maintenance operation on this pool.
+    fn dump_metadata(&self) -> Result<ThinMetadata> {
+        let _snap_guard = MetadataSnapGuard::reserve(&self.name, self.backend)?;
+
+        let output = Command::new("thin_dump")
+            .arg("--metadata-snap")
+            .arg("-f")
+            .arg("xml")
+            .arg(self.metadata_dev.device_path())
+            .output()
+            .context("Failed to run thin_dump")?;
+
+        if !output.status.success() {
+            anyhow::bail!("thin_dump failed with status: {}", output.status);
+        }
+
+        let xml =
+            String::from_utf8(output.stdout).context("thin_dump output is not valid UTF-8")?;
+        parse_thin_dump_xml(&xml)
+    }
+}
+
+/// RAII guard around a pool's `reserve_metadata_snap`/`release_metadata_snap`
+/// dmsetup messages. Device-mapper only allows one metadata snapshot per pool
+/// to be held at a time, so the release message must run even if the caller
+/// bails out early or `thin_dump` fails while the snapshot is held.
+struct MetadataSnapGuard<'a> {
+    pool_name: &'a str,
+    backend: Backend,
+}
+
+impl<'a> MetadataSnapGuard<'a> {
+    fn reserve(pool_name: &'a str, backend: Backend) -> Result<Self> {
+        dm_message(pool_name, "reserve_metadata_snap", backend).with_context(|| {
+            format!(
+                "Failed to reserve metadata snapshot for pool: {}",
+                pool_name
+            )
+        })?;
+        Ok(Self { pool_name, backend })
+    }
+}
+
+impl<'a> Drop for MetadataSnapGuard<'a> {
+    fn drop(&mut self) {
+        if let Err(err) = dm_message(self.pool_name, "release_metadata_snap", self.backend) {
+            eprintln!(
+                "Failed to release metadata snapshot for pool {}: {}",
+                self.pool_name, err
+            );
+        }
+    }
 }
 
 impl Drop for ThinPool {
@@ -355,6 +838,227 @@ impl Drop for ThinPool {
     }
 }
 
+/// Operating mode reported in a thin-pool status line.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ThinPoolMode {
+    ReadWrite,
+    ReadOnly,
+    OutOfDataSpace,
+}
+
+/// Parsed `dmsetup status`/`DM_TABLE_STATUS` output for a thin-pool device.
+/// Field order follows the thin-pool target's status format:
+/// `<transaction_id> <used_metadata>/<total_metadata> <used_data>/<total_data>
+/// <held_root> <needs_check|-> <rw|ro|out_of_data_space> ...`.
+#[derive(Debug, Clone, Copy)]
+struct ThinPoolStatus {
+    transaction_id: u64,
+    used_metadata_blocks: u64,
+    total_metadata_blocks: u64,
+    used_data_blocks: u64,
+    total_data_blocks: u64,
+    held_metadata_root: Option<u64>,
+    needs_check: bool,
+    mode: ThinPoolMode,
+}
+
+impl ThinPoolStatus {
+    fn parse(status_line: &str) -> Result<Self> {
+        let mut fields = status_line.split_whitespace();
+
+        let transaction_id = fields
+            .next()
+            .context("Missing transaction_id in thin-pool status")?
+            .parse()
+            .context("Invalid transaction_id in thin-pool status")?;
+
+        let (used_metadata_blocks, total_metadata_blocks) = parse_fraction(
+            fields
+                .next()
+                .context("Missing metadata usage in thin-pool status")?,
+        )?;
+        let (used_data_blocks, total_data_blocks) = parse_fraction(
+            fields
+                .next()
+                .context("Missing data usage in thin-pool status")?,
+        )?;
+
+        let held_metadata_root = match fields
+            .next()
+            .context("Missing held metadata root in thin-pool status")?
+        {
+            "-" => None,
+            value => Some(
+                value
+                    .parse()
+                    .context("Invalid held metadata root in thin-pool status")?,
+            ),
+        };
+
+        let needs_check = fields
+            .next()
+            .context("Missing needs_check field in thin-pool status")?
+            == "needs_check";
+
+        let mode = match fields
+            .next()
+            .context("Missing mode field in thin-pool status")?
+        {
+            "rw" => ThinPoolMode::ReadWrite,
+            "ro" => ThinPoolMode::ReadOnly,
+            "out_of_data_space" => ThinPoolMode::OutOfDataSpace,
+            other => anyhow::bail!("Unrecognized thin-pool mode: {}", other),
+        };
+
+        Ok(Self {
+            transaction_id,
+            used_metadata_blocks,
+            total_metadata_blocks,
+            used_data_blocks,
+            total_data_blocks,
+            held_metadata_root,
+            needs_check,
+            mode,
+        })
+    }
+}
+
+/// Parses a `<used>/<total>` status field, e.g. `"42/256"`.
+fn parse_fraction(field: &str) -> Result<(u64, u64)> {
+    let (used, total) = field
+        .split_once('/')
+        .with_context(|| format!("Expected <used>/<total>, got: {}", field))?;
+    Ok((
+        used.parse().with_context(|| format!("Invalid used count: {}", used))?,
+        total.parse().with_context(|| format!("Invalid total count: {}", total))?,
+    ))
+}
+
+/// A single contiguous run of blocks mapped from a thin device's logical
+/// address space onto the pool's shared data device. `thin_dump` emits a
+/// `<range_mapping>` for runs longer than one block and a `<single_mapping>`
+/// for isolated blocks; both are folded into this one shape since the run
+/// length is all that differs between them.
+#[derive(Debug, Clone, Copy)]
+struct ThinMapping {
+    origin_begin: u64,
+    data_begin: u64,
+    length: u64,
+}
+
+/// Metadata for a single thin device as recorded in a `thin_dump` snapshot.
+#[derive(Debug, Clone)]
+struct ThinDeviceMetadata {
+    device_id: u32,
+    mapped_blocks: u64,
+    mappings: Vec<ThinMapping>,
+}
+
+/// Parsed `thin_dump --metadata-snap` output: every thin device known to the
+/// pool's metadata, each with the block ranges it maps onto the data device.
+#[derive(Debug, Clone)]
+struct ThinMetadata {
+    devices: Vec<ThinDeviceMetadata>,
+}
+
+/// Parses the XML produced by `thin_dump --metadata-snap -f xml` into a
+/// structured `ThinMetadata`. Only the fields relevant to mapping layout are
+/// extracted; unrecognized elements and attributes are ignored so this stays
+/// forward-compatible with newer thin-provisioning-tools XML versions.
+fn parse_thin_dump_xml(xml: &str) -> Result<ThinMetadata> {
+    let mut reader = Reader::from_str(xml);
+    reader.config_mut().trim_text(true);
+
+    let mut devices = Vec::new();
+    let mut current_device: Option<ThinDeviceMetadata> = None;
+    let mut buf = Vec::new();
+
+    loop {
+        match reader
+            .read_event_into(&mut buf)
+            .context("Failed to parse thin_dump XML")?
+        {
+            Event::Start(tag) if tag.name().as_ref() == b"device" => {
+                current_device = Some(parse_device_element(&tag)?);
+            }
+            Event::End(tag) if tag.name().as_ref() == b"device" => {
+                if let Some(device) = current_device.take() {
+                    devices.push(device);
+                }
+            }
+            Event::Empty(tag) if tag.name().as_ref() == b"range_mapping" => {
+                let mapping = parse_range_mapping(&tag)?;
+                if let Some(device) = current_device.as_mut() {
+                    device.mappings.push(mapping);
+                }
+            }
+            Event::Empty(tag) if tag.name().as_ref() == b"single_mapping" => {
+                let mapping = parse_single_mapping(&tag)?;
+                if let Some(device) = current_device.as_mut() {
+                    device.mappings.push(mapping);
+                }
+            }
+            Event::Eof => break,
+            _ => {}
+        }
+        buf.clear();
+    }
+
+    Ok(ThinMetadata { devices })
+}
+
+/// Reads a required attribute's unescaped string value off an XML start tag.
+fn xml_attr(tag: &BytesStart, name: &str) -> Result<String> {
+    for attr in tag.attributes() {
+        let attr = attr.context("Invalid attribute in thin_dump XML")?;
+        if attr.key.as_ref() == name.as_bytes() {
+            return Ok(attr
+                .unescape_value()
+                .context("Invalid attribute value in thin_dump XML")?
+                .into_owned());
+        }
+    }
+    anyhow::bail!("Missing `{}` attribute in thin_dump XML", name)
+}
+
+fn parse_device_element(tag: &BytesStart) -> Result<ThinDeviceMetadata> {
+    Ok(ThinDeviceMetadata {
+        device_id: xml_attr(tag, "dev_id")?
+            .parse()
+            .context("Invalid dev_id in thin_dump XML")?,
+        mapped_blocks: xml_attr(tag, "mapped_blocks")?
+            .parse()
+            .context("Invalid mapped_blocks in thin_dump XML")?,
+        mappings: Vec::new(),
+    })
+}
+
+fn parse_range_mapping(tag: &BytesStart) -> Result<ThinMapping> {
+    Ok(ThinMapping {
+        origin_begin: xml_attr(tag, "origin_begin")?
+            .parse()
+            .context("Invalid origin_begin in thin_dump XML")?,
+        data_begin: xml_attr(tag, "data_begin")?
+            .parse()
+            .context("Invalid data_begin in thin_dump XML")?,
+        length: xml_attr(tag, "length")?
+            .parse()
+            .context("Invalid length in thin_dump XML")?,
+    })
+}
+
+fn parse_single_mapping(tag: &BytesStart) -> Result<ThinMapping> {
+    Ok(ThinMapping {
+        origin_begin: xml_attr(tag, "origin_block")?
+            .parse()
+            .context("Invalid origin_block in thin_dump XML")?,
+        data_begin: xml_attr(tag, "data_block")?
+            .parse()
+            .context("Invalid data_block in thin_dump XML")?,
+        length: 1,
+    })
+}
+
 /// Represents a thin volume created from a thin pool.
 struct ThinVolume {
     /// Name of the volume (/dev/mapper/<name>)
@@ -366,8 +1070,15 @@ struct ThinVolume {
     /// Unique device ID within the pool
     device_id: u32,
 
-    /// Virutal size in megabytes
-    virtual_size_mb: u64,
+    /// Virtual size in 512-byte sectors. Kept as the source of truth rather
+    /// than a rounded megabyte value, since round-tripping through whole
+    /// megabytes would silently truncate volumes whose size isn't an exact
+    /// multiple of 1MiB (e.g. those sized from an external origin device).
+    virtual_size_sectors: u64,
+
+    /// Which backend was used to create this volume, so `create_snapshot`
+    /// and `remove` operate on it the same way.
+    backend: Backend,
 
     /// Whether this volume should be cleaned up when dropped
     should_cleanup: bool,
@@ -379,42 +1090,31 @@ impl ThinVolume {
         PathBuf::from(format!("/dev/mapper/{}", self.name))
     }
 
+    /// Queries and parses the volume's current `dmsetup status` line.
+    fn status(&self) -> Result<ThinStatus> {
+        let status_line = dm_status(&self.name, self.backend)
+            .with_context(|| format!("Failed to query status for volume: {}", self.name))?;
+        ThinStatus::parse(&status_line)
+    }
+
     /// Creates a snapshot of this thin volume.
     fn create_snapshot(&self, snapshot_name: &str, snapshot_device_id: u32) -> Result<ThinVolume> {
         let message = format!("create_snap {} {}", snapshot_device_id, self.device_id);
-        let status = Command::new("dmsetup")
-            .arg("message")
-            .arg(&format!("/dev/mapper/{}", self.pool_name))
-            .arg("0")
-            .arg(&message)
-            .status()
-            .context("Failed to send create_snap message")?;
-        if !status.success() {
-            anyhow::bail!("Failed to create snapshot with ID {}", snapshot_device_id)
-        }
+        dm_message(&self.pool_name, &message, self.backend)
+            .with_context(|| format!("Failed to create snapshot with ID {}", snapshot_device_id))?;
 
-        let virtual_size_sectors = self.virtual_size_mb * 1024 * 1024 / 512;
         let table = format!(
             "0 {} thin /dev/mapper/{} {}",
-            virtual_size_sectors, self.pool_name, snapshot_device_id,
+            self.virtual_size_sectors, self.pool_name, snapshot_device_id,
         );
-        let status = Command::new("dmsetup")
-            .arg("create")
-            .arg(snapshot_name)
-            .arg("--table")
-            .arg(&table)
-            .arg("--verifyudev")
-            .status()
-            .context("Failed to create snapshot device")?;
-        if !status.success() {
-            anyhow::bail!("dmsetup create failed for snapshot: {}", snapshot_name);
-        }
+        dm_create_device(snapshot_name, &table, self.backend)?;
 
         Ok(ThinVolume {
             name: snapshot_name.to_string(),
             pool_name: self.pool_name.clone(),
             device_id: snapshot_device_id,
-            virtual_size_mb: self.virtual_size_mb,
+            virtual_size_sectors: self.virtual_size_sectors,
+            backend: self.backend,
             should_cleanup: true,
         })
     }
@@ -425,27 +1125,14 @@ impl ThinVolume {
             return Ok(());
         }
 
-        let status = Command::new("dmsetup")
-            .arg("remove")
-            .arg(&self.name)
-            .status()
-            .context("Failed to remove thin volume device")?;
-        if !status.success() {
-            anyhow::bail!("Failed to remove volume: {}", self.name);
-        }
+        dm_remove_device(&self.name, self.backend)
+            .with_context(|| format!("Failed to remove volume: {}", self.name))?;
 
         let message = format!("delete {}", self.device_id);
-        let status = Command::new("dmsetup")
-            .arg("message")
-            .arg(&format!("/dev/mapper/{}", self.pool_name))
-            .arg("0")
-            .arg(&message)
-            .status()
-            .context("Failed to send delete message")?;
-        if !status.success() {
+        if let Err(err) = dm_message(&self.pool_name, &message, self.backend) {
             eprintln!(
-                "Warning: Failed to delete thin device {} from pool",
-                self.device_id
+                "Warning: Failed to delete thin device {} from pool: {}",
+                self.device_id, err
             );
         }
 
@@ -460,15 +1147,679 @@ impl Drop for ThinVolume {
     }
 }
 
+/// A dm-crypt `aes-xts-plain64` layer stacked on top of a thin volume (or one
+/// of its snapshots), so data only ever reaches the pool encrypted. Wrapping
+/// a snapshot this way lets a base volume stay plaintext while only the
+/// snapshot taken from it is encrypted at rest.
+struct EncryptedVolume {
+    /// Name of the crypt mapping (/dev/mapper/<name>)
+    name: String,
+
+    /// The thin volume or snapshot this crypt mapping is layered over.
+    inner: ThinVolume,
+
+    /// Which backend created the crypt mapping, so `remove` tears it down
+    /// the same way.
+    backend: Backend,
+
+    /// Whether this mapping should be cleaned up when dropped
+    should_cleanup: bool,
+}
+
+impl EncryptedVolume {
+    /// Creates a crypt mapping over `inner` using `key_hex` as the raw
+    /// encryption key, exactly as `dmsetup`/`cryptsetup` expect it on the
+    /// crypt target's table line (e.g. 64 hex chars for an aes-128-xts key).
+    fn create(crypt_name: &str, inner: ThinVolume, key_hex: &str) -> Result<Self> {
+        let backend = inner.backend;
+        let table = format!(
+            "0 {} crypt aes-xts-plain64 {} 0 /dev/mapper/{} 0",
+            inner.virtual_size_sectors, key_hex, inner.name
+        );
+        dm_create_device(crypt_name, &table, backend)?;
+
+        Ok(Self {
+            name: crypt_name.to_string(),
+            inner,
+            backend,
+            should_cleanup: true,
+        })
+    }
+
+    /// Returns the path to the decrypted block device.
+    fn device_path(&self) -> PathBuf {
+        PathBuf::from(format!("/dev/mapper/{}", self.name))
+    }
+
+    /// Removes the crypt mapping and then the thin volume it wraps, in that
+    /// order — device-mapper won't let the thin volume be torn down while
+    /// the crypt target still holds it open.
+    fn remove(&mut self) -> Result<()> {
+        if !self.should_cleanup {
+            return Ok(());
+        }
+
+        dm_remove_device(&self.name, self.backend)
+            .with_context(|| format!("Failed to remove crypt device: {}", self.name))?;
+        self.inner.remove()?;
+
+        self.should_cleanup = false;
+        Ok(())
+    }
+}
+
+impl Drop for EncryptedVolume {
+    fn drop(&mut self) {
+        let _ = self.remove();
+    }
+}
+
+/// Parsed `dmsetup status`/`DM_TABLE_STATUS` output for a thin device:
+/// `<nr mapped sectors> <highest mapped sector>`, where the highest mapped
+/// sector field is `-` for a device with no mappings yet.
+#[derive(Debug, Clone, Copy)]
+struct ThinStatus {
+    mapped_sectors: u64,
+    highest_mapped_sector: Option<u64>,
+}
+
+impl ThinStatus {
+    fn parse(status_line: &str) -> Result<Self> {
+        let mut fields = status_line.split_whitespace();
+
+        let mapped_sectors = fields
+            .next()
+            .context("Missing mapped sector count in thin status")?
+            .parse()
+            .context("Invalid mapped sector count in thin status")?;
+
+        let highest_mapped_sector = match fields
+            .next()
+            .context("Missing highest mapped sector in thin status")?
+        {
+            "-" => None,
+            value => Some(
+                value
+                    .parse()
+                    .context("Invalid highest mapped sector in thin status")?,
+            ),
+        };
+
+        Ok(Self {
+            mapped_sectors,
+            highest_mapped_sector,
+        })
+    }
+}
+
+/// Creates a device-mapper device named `name` with the given table line and
+/// brings it online, via `dmsetup create --table` or `DM_DEV_CREATE` +
+/// `DM_TABLE_LOAD` + `DM_DEV_SUSPEND` (resume), depending on `backend`.
+fn dm_create_device(name: &str, table: &str, backend: Backend) -> Result<()> {
+    match backend {
+        Backend::Shell => {
+            let status = Command::new("dmsetup")
+                .arg("create")
+                .arg(name)
+                .arg("--table")
+                .arg(table)
+                .arg("--verifyudev")
+                .status()
+                .context("Failed to execute dmsetup create")?;
+            if !status.success() {
+                anyhow::bail!("dmsetup create failed for device: {}", name);
+            }
+            Ok(())
+        }
+        Backend::Ioctl => {
+            let dm = dm::Dm::open()?;
+            dm.dev_create(name)
+                .with_context(|| format!("DM_DEV_CREATE failed for device: {}", name))?;
+            dm.table_load(name, table)
+                .with_context(|| format!("DM_TABLE_LOAD failed for device: {}", name))?;
+            dm.dev_suspend(name, false)
+                .with_context(|| format!("DM_DEV_SUSPEND (resume) failed for device: {}", name))
+        }
+    }
+}
+
+/// Removes a device-mapper device, via `dmsetup remove` or `DM_DEV_REMOVE`.
+fn dm_remove_device(name: &str, backend: Backend) -> Result<()> {
+    match backend {
+        Backend::Shell => {
+            let status = Command::new("dmsetup")
+                .arg("remove")
+                .arg(name)
+                .status()
+                .context("Failed to execute dmsetup remove")?;
+            if !status.success() {
+                anyhow::bail!("dmsetup remove failed for device: {}", name);
+            }
+            Ok(())
+        }
+        Backend::Ioctl => dm::Dm::open()?.dev_remove(name),
+    }
+}
+
+/// Sends a one-line message to a device-mapper target (e.g. the thin-pool
+/// messages `create_thin`/`create_snap`/`delete`), via `dmsetup message` or
+/// `DM_TARGET_MSG`.
+fn dm_message(target_name: &str, message: &str, backend: Backend) -> Result<()> {
+    match backend {
+        Backend::Shell => {
+            let status = Command::new("dmsetup")
+                .arg("message")
+                .arg(format!("/dev/mapper/{}", target_name))
+                .arg("0")
+                .arg(message)
+                .status()
+                .context("Failed to execute dmsetup message")?;
+            if !status.success() {
+                anyhow::bail!("dmsetup message {:?} failed for {}", message, target_name);
+            }
+            Ok(())
+        }
+        Backend::Ioctl => dm::Dm::open()?.target_msg(target_name, 0, message),
+    }
+}
+
+/// Returns a device's status string (everything after `<start> <len>
+/// <target_type>` in `dmsetup status` output), via `dmsetup status` or
+/// `DM_TABLE_STATUS`.
+fn dm_status(name: &str, backend: Backend) -> Result<String> {
+    match backend {
+        Backend::Shell => {
+            let output = Command::new("dmsetup")
+                .arg("status")
+                .arg(name)
+                .output()
+                .context("Failed to execute dmsetup status")?;
+            if !output.status.success() {
+                anyhow::bail!("dmsetup status failed for device: {}", name);
+            }
+            let line = String::from_utf8(output.stdout)
+                .context("dmsetup status output is not valid UTF-8")?;
+            let status = line
+                .trim()
+                .splitn(4, ' ')
+                .nth(3)
+                .with_context(|| format!("Malformed dmsetup status line for {}: {:?}", name, line))?
+                .to_string();
+            Ok(status)
+        }
+        Backend::Ioctl => dm::Dm::open()?.table_status(name),
+    }
+}
+
+/// Native device-mapper and loop-device ioctl backend, issuing the same
+/// `DM_IOCTL`s and `LOOP_*` ioctls that `dmsetup`/`losetup` issue internally
+/// (see `Documentation/admin-guide/device-mapper/dm-ioctl.rst` and
+/// `<linux/dm-ioctl.h>`/`<linux/loop.h>`), so callers avoid a process spawn
+/// and stdout-parsing per operation.
+mod dm {
+    use anyhow::{Context, Result};
+    use std::{
+        fs::{File, OpenOptions},
+        mem,
+        os::unix::io::AsRawFd,
+        path::Path,
+    };
+
+    /// Major number device-mapper ioctls are issued against, and the base of
+    /// the `dm_ioctl` structure's version this code was written against.
+    const DM_IOCTL: u8 = 0xfd;
+    const DM_VERSION_MAJOR: u32 = 4;
+
+    const DM_DEV_CREATE_CMD: u8 = 3;
+    const DM_DEV_REMOVE_CMD: u8 = 4;
+    const DM_DEV_SUSPEND_CMD: u8 = 6;
+    const DM_TABLE_LOAD_CMD: u8 = 9;
+    const DM_TABLE_STATUS_CMD: u8 = 12;
+    const DM_TARGET_MSG_CMD: u8 = 14;
+
+    const DM_NAME_LEN: usize = 128;
+    const DM_UUID_LEN: usize = 129;
+
+    /// Mirrors `struct dm_ioctl` from `<linux/dm-ioctl.h>`: the fixed-size
+    /// header every `DM_IOCTL` command reads and/or writes, followed by a
+    /// variable-length payload (target specs, messages) appended after it in
+    /// the same buffer.
+    #[repr(C)]
+    #[derive(Clone)]
+    struct DmIoctl {
+        version: [u32; 3],
+        data_size: u32,
+        data_start: u32,
+        target_count: u32,
+        open_count: i32,
+        flags: u32,
+        event_nr: u32,
+        padding: u32,
+        dev: u64,
+        name: [u8; DM_NAME_LEN],
+        uuid: [u8; DM_UUID_LEN],
+        data: [u8; 7], // pads the struct to an 8-byte-aligned 312 bytes.
+    }
+
+    impl DmIoctl {
+        fn new(name: &str) -> Result<Self> {
+            if name.len() >= DM_NAME_LEN {
+                anyhow::bail!("device name too long for DM_IOCTL: {}", name);
+            }
+            let mut ioctl = Self {
+                version: [DM_VERSION_MAJOR, 0, 0],
+                data_size: mem::size_of::<Self>() as u32,
+                data_start: mem::size_of::<Self>() as u32,
+                target_count: 0,
+                open_count: 0,
+                flags: 0,
+                event_nr: 0,
+                padding: 0,
+                dev: 0,
+                name: [0; DM_NAME_LEN],
+                uuid: [0; DM_UUID_LEN],
+                data: [0; 7],
+            };
+            ioctl.name[..name.len()].copy_from_slice(name.as_bytes());
+            Ok(ioctl)
+        }
+    }
+
+    nix::ioctl_readwrite!(dm_dev_create, DM_IOCTL, DM_DEV_CREATE_CMD, DmIoctl);
+    nix::ioctl_readwrite!(dm_dev_remove, DM_IOCTL, DM_DEV_REMOVE_CMD, DmIoctl);
+    nix::ioctl_readwrite!(dm_dev_suspend, DM_IOCTL, DM_DEV_SUSPEND_CMD, DmIoctl);
+    nix::ioctl_readwrite!(dm_table_load, DM_IOCTL, DM_TABLE_LOAD_CMD, DmIoctl);
+    nix::ioctl_readwrite!(dm_table_status, DM_IOCTL, DM_TABLE_STATUS_CMD, DmIoctl);
+    nix::ioctl_readwrite!(dm_target_msg, DM_IOCTL, DM_TARGET_MSG_CMD, DmIoctl);
+
+    /// Handle to the device-mapper control node (`/dev/mapper/control`),
+    /// through which every `DM_IOCTL` command is issued.
+    pub struct Dm {
+        control: File,
+    }
+
+    impl Dm {
+        pub fn open() -> Result<Self> {
+            let control = OpenOptions::new()
+                .read(true)
+                .write(true)
+                .open("/dev/mapper/control")
+                .context("Failed to open /dev/mapper/control")?;
+            Ok(Self { control })
+        }
+
+        /// `DM_DEV_CREATE`: registers a new (initially table-less) device
+        /// named `name`.
+        pub fn dev_create(&self, name: &str) -> Result<()> {
+            let mut ioctl = DmIoctl::new(name)?;
+            unsafe { dm_dev_create(self.control.as_raw_fd(), &mut ioctl) }
+                .context("DM_DEV_CREATE ioctl failed")?;
+            Ok(())
+        }
+
+        /// `DM_DEV_REMOVE`: unregisters a device, which must not be in use.
+        pub fn dev_remove(&self, name: &str) -> Result<()> {
+            let mut ioctl = DmIoctl::new(name)?;
+            unsafe { dm_dev_remove(self.control.as_raw_fd(), &mut ioctl) }
+                .context("DM_DEV_REMOVE ioctl failed")?;
+            Ok(())
+        }
+
+        /// `DM_TABLE_LOAD`: loads a single-line target table (e.g.
+        /// `"0 <len> thin-pool ..."`) as the device's inactive table; takes
+        /// effect once the device is suspended and resumed.
+        pub fn table_load(&self, name: &str, table_line: &str) -> Result<()> {
+            let mut ioctl = DmIoctl::new(name)?;
+            let spec = TargetSpec::parse(table_line)?;
+            let payload = spec.encode();
+
+            let header_size = mem::size_of::<DmIoctl>();
+            ioctl.target_count = 1;
+            ioctl.data_size = (header_size + payload.len()) as u32;
+            ioctl.data_start = header_size as u32;
+
+            let mut buf = vec![0u8; header_size + payload.len()];
+            // SAFETY: `DmIoctl` is `#[repr(C)]` and we only ever read the
+            // header back out through the same type, so a byte-for-byte
+            // copy into the start of `buf` round-trips correctly.
+            unsafe {
+                std::ptr::copy_nonoverlapping(
+                    &ioctl as *const DmIoctl as *const u8,
+                    buf.as_mut_ptr(),
+                    header_size,
+                );
+            }
+            buf[header_size..].copy_from_slice(&payload);
+
+            unsafe { dm_table_load(self.control.as_raw_fd(), buf.as_mut_ptr() as *mut DmIoctl) }
+                .context("DM_TABLE_LOAD ioctl failed")?;
+            Ok(())
+        }
+
+        /// `DM_DEV_SUSPEND`: suspends the device (blocking new I/O) when
+        /// `suspend` is true, swaps in the inactive table, or resumes it
+        /// (flag clear) to make a freshly-loaded table live.
+        pub fn dev_suspend(&self, name: &str, suspend: bool) -> Result<()> {
+            const DM_SUSPEND_FLAG: u32 = 1 << 0;
+            let mut ioctl = DmIoctl::new(name)?;
+            if suspend {
+                ioctl.flags |= DM_SUSPEND_FLAG;
+            }
+            unsafe { dm_dev_suspend(self.control.as_raw_fd(), &mut ioctl) }
+                .context("DM_DEV_SUSPEND ioctl failed")?;
+            Ok(())
+        }
+
+        /// `DM_TABLE_STATUS`: returns the target's status string (everything
+        /// after `<start> <len> <target_type>` in `dmsetup status` output),
+        /// growing the ioctl buffer and retrying if the kernel reports the
+        /// status didn't fit the first time.
+        pub fn table_status(&self, name: &str) -> Result<String> {
+            let header_size = mem::size_of::<DmIoctl>();
+            let mut buf_size = header_size + 512;
+
+            loop {
+                let mut buf = vec![0u8; buf_size];
+                let mut ioctl = DmIoctl::new(name)?;
+                ioctl.data_size = buf_size as u32;
+                ioctl.data_start = header_size as u32;
+                unsafe {
+                    std::ptr::copy_nonoverlapping(
+                        &ioctl as *const DmIoctl as *const u8,
+                        buf.as_mut_ptr(),
+                        header_size,
+                    );
+                }
+
+                unsafe { dm_table_status(self.control.as_raw_fd(), buf.as_mut_ptr() as *mut DmIoctl) }
+                    .context("DM_TABLE_STATUS ioctl failed")?;
+
+                // SAFETY: the kernel wrote a `DmIoctl` header back into the
+                // start of `buf`, matching the layout we read it with.
+                let result: DmIoctl =
+                    unsafe { std::ptr::read_unaligned(buf.as_ptr() as *const DmIoctl) };
+
+                const DM_BUFFER_FULL_FLAG: u32 = 1 << 8;
+                if result.flags & DM_BUFFER_FULL_FLAG != 0 {
+                    buf_size = result.data_size as usize;
+                    continue;
+                }
+
+                if result.target_count == 0 {
+                    anyhow::bail!("device {} has no targets loaded", name);
+                }
+
+                // Skip past the `struct dm_target_spec` header (sector_start,
+                // length, status, next, target_type[16]) to the status
+                // string the target itself reported.
+                const DM_MAX_TYPE_NAME: usize = 16;
+                let spec_header_size = 8 + 8 + 4 + 4 + DM_MAX_TYPE_NAME;
+                let status_start = header_size + spec_header_size;
+                let status_bytes = &buf[status_start..result.data_size as usize];
+                let status_end = status_bytes
+                    .iter()
+                    .position(|&b| b == 0)
+                    .unwrap_or(status_bytes.len());
+                return Ok(String::from_utf8_lossy(&status_bytes[..status_end]).into_owned());
+            }
+        }
+
+        /// `DM_TARGET_MSG`: sends a target-specific message (e.g.
+        /// `create_thin 0`) to the device at the given logical sector.
+        pub fn target_msg(&self, name: &str, sector: u64, message: &str) -> Result<()> {
+            let mut ioctl = DmIoctl::new(name)?;
+
+            #[repr(C)]
+            struct DmTargetMsg {
+                sector: u64,
+                // `message` (a NUL-terminated string) follows inline.
+            }
+
+            let header_size = mem::size_of::<DmIoctl>();
+            let msg_header_size = mem::size_of::<DmTargetMsg>();
+            let message_bytes_len = message.len() + 1; // NUL terminator
+            let total = header_size + msg_header_size + message_bytes_len;
+
+            ioctl.data_size = total as u32;
+            ioctl.data_start = header_size as u32;
+
+            let mut buf = vec![0u8; total];
+            unsafe {
+                std::ptr::copy_nonoverlapping(
+                    &ioctl as *const DmIoctl as *const u8,
+                    buf.as_mut_ptr(),
+                    header_size,
+                );
+            }
+            buf[header_size..header_size + 8].copy_from_slice(&sector.to_ne_bytes());
+            buf[header_size + msg_header_size..header_size + msg_header_size + message.len()]
+                .copy_from_slice(message.as_bytes());
+
+            unsafe { dm_target_msg(self.control.as_raw_fd(), buf.as_mut_ptr() as *mut DmIoctl) }
+                .context("DM_TARGET_MSG ioctl failed")?;
+            Ok(())
+        }
+    }
+
+    /// A single parsed device-mapper table line (`<start> <len> <target>
+    /// <target args...>`), ready to be encoded as the `struct
+    /// dm_target_spec` + argument string payload `DM_TABLE_LOAD` expects.
+    struct TargetSpec {
+        sector_start: u64,
+        length: u64,
+        target_type: String,
+        args: String,
+    }
+
+    impl TargetSpec {
+        fn parse(table_line: &str) -> Result<Self> {
+            let mut parts = table_line.splitn(4, ' ');
+            let sector_start = parts
+                .next()
+                .context("Missing start sector in table line")?
+                .parse()
+                .context("Invalid start sector in table line")?;
+            let length = parts
+                .next()
+                .context("Missing length in table line")?
+                .parse()
+                .context("Invalid length in table line")?;
+            let target_type = parts
+                .next()
+                .context("Missing target type in table line")?
+                .to_string();
+            let args = parts.next().unwrap_or("").to_string();
+            Ok(Self {
+                sector_start,
+                length,
+                target_type,
+                args,
+            })
+        }
+
+        /// Encodes as `struct dm_target_spec` (sector_start, length,
+        /// status, next, target_type[16]) immediately followed by the
+        /// NUL-terminated argument string, then padded to an 8-byte
+        /// boundary as the kernel requires between consecutive specs.
+        fn encode(&self) -> Vec<u8> {
+            const DM_MAX_TYPE_NAME: usize = 16;
+            let mut target_type = [0u8; DM_MAX_TYPE_NAME];
+            let len = self.target_type.len().min(DM_MAX_TYPE_NAME - 1);
+            target_type[..len].copy_from_slice(&self.target_type.as_bytes()[..len]);
+
+            let spec_header_size = 8 + 8 + 4 + 4 + DM_MAX_TYPE_NAME; // sector_start, length, status, next, target_type
+            let args_len = self.args.len() + 1; // NUL terminator
+            let unpadded = spec_header_size + args_len;
+            let padded = unpadded.div_ceil(8) * 8;
+
+            let mut buf = vec![0u8; padded];
+            buf[0..8].copy_from_slice(&self.sector_start.to_ne_bytes());
+            buf[8..16].copy_from_slice(&self.length.to_ne_bytes());
+            // status (4 bytes) and next (4 bytes) are left zeroed: there is
+            // only one target spec, so `next` (the byte offset to the next
+            // spec) is unused, and `status` is an output-only field.
+            buf[16..16 + DM_MAX_TYPE_NAME].copy_from_slice(&target_type);
+            buf[spec_header_size..spec_header_size + self.args.len()]
+                .copy_from_slice(self.args.as_bytes());
+
+            buf
+        }
+    }
+
+    const LOOP_CTL_GET_FREE: u8 = 0x82;
+    const LOOP_SET_FD: u8 = 0x00;
+    const LOOP_CLR_FD: u8 = 0x01;
+    const LOOP_SET_STATUS64: u8 = 0x04;
+    const LOOP_SET_CAPACITY: u8 = 0x07;
+    const LOOP_MAJOR: u8 = 0x4C;
+
+    /// Mirrors `struct loop_info64` from `<linux/loop.h>`.
+    #[repr(C)]
+    #[derive(Clone, Copy)]
+    struct LoopInfo64 {
+        lo_device: u64,
+        lo_inode: u64,
+        lo_rdevice: u64,
+        lo_offset: u64,
+        lo_sizelimit: u64,
+        lo_number: u32,
+        lo_encrypt_type: u32,
+        lo_encrypt_key_size: u32,
+        lo_flags: u32,
+        lo_file_name: [u8; 64],
+        lo_crypt_name: [u8; 64],
+        lo_encrypt_key: [u8; 32],
+        lo_init: [u64; 2],
+    }
+
+    nix::ioctl_none!(loop_ctl_get_free, LOOP_MAJOR, LOOP_CTL_GET_FREE);
+    // LOOP_SET_FD and LOOP_SET_STATUS64 are legacy ioctls: <linux/loop.h>
+    // defines them as bare numbers (0x4C00, 0x4C04) rather than encoding
+    // direction/size the way `_IOW` does, so the regular `ioctl_write_*!`
+    // macros compute the wrong request code for them. Use the `_bad`
+    // variants with a literal request code instead.
+    nix::ioctl_write_int_bad!(loop_set_fd, nix::request_code_none!(LOOP_MAJOR, LOOP_SET_FD));
+    nix::ioctl_none!(loop_clr_fd, LOOP_MAJOR, LOOP_CLR_FD);
+    nix::ioctl_write_ptr_bad!(
+        loop_set_status64,
+        nix::request_code_none!(LOOP_MAJOR, LOOP_SET_STATUS64),
+        LoopInfo64
+    );
+    nix::ioctl_none!(loop_set_capacity_ioctl, LOOP_MAJOR, LOOP_SET_CAPACITY);
+
+    /// Attaches `backing_file` to a free loop device found via
+    /// `LOOP_CTL_GET_FREE` on `/dev/loop-control`, returning its path.
+    pub fn loop_attach(backing_file: &Path) -> Result<std::path::PathBuf> {
+        let ctl = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .open("/dev/loop-control")
+            .context("Failed to open /dev/loop-control")?;
+        let loop_number = unsafe { loop_ctl_get_free(ctl.as_raw_fd()) }
+            .context("LOOP_CTL_GET_FREE ioctl failed")?;
+
+        let device_path = std::path::PathBuf::from(format!("/dev/loop{}", loop_number));
+        let loop_dev = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .open(&device_path)
+            .with_context(|| format!("Failed to open {:?}", device_path))?;
+        let backing = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .open(backing_file)
+            .with_context(|| format!("Failed to open backing file: {:?}", backing_file))?;
+
+        unsafe { loop_set_fd(loop_dev.as_raw_fd(), backing.as_raw_fd()) }
+            .context("LOOP_SET_FD ioctl failed")?;
+
+        let mut info = LoopInfo64 {
+            lo_device: 0,
+            lo_inode: 0,
+            lo_rdevice: 0,
+            lo_offset: 0,
+            lo_sizelimit: 0,
+            lo_number: loop_number as u32,
+            lo_encrypt_type: 0,
+            lo_encrypt_key_size: 0,
+            lo_flags: 0,
+            lo_file_name: [0; 64],
+            lo_crypt_name: [0; 64],
+            lo_encrypt_key: [0; 32],
+            lo_init: [0; 2],
+        };
+        let name_bytes = backing_file.to_string_lossy();
+        let name_bytes = name_bytes.as_bytes();
+        let copy_len = name_bytes.len().min(info.lo_file_name.len() - 1);
+        info.lo_file_name[..copy_len].copy_from_slice(&name_bytes[..copy_len]);
+
+        if let Err(err) = unsafe { loop_set_status64(loop_dev.as_raw_fd(), &info) } {
+            let _ = unsafe { loop_clr_fd(loop_dev.as_raw_fd()) };
+            return Err(err).context("LOOP_SET_STATUS64 ioctl failed");
+        }
+
+        Ok(device_path)
+    }
+
+    /// Tells the kernel to re-read a loop device's backing file size via
+    /// `LOOP_SET_CAPACITY`, after the backing file has been grown.
+    pub fn loop_set_capacity(device_path: &Path) -> Result<()> {
+        let loop_dev = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .open(device_path)
+            .with_context(|| format!("Failed to open {:?}", device_path))?;
+        unsafe { loop_set_capacity_ioctl(loop_dev.as_raw_fd()) }
+            .context("LOOP_SET_CAPACITY ioctl failed")?;
+        Ok(())
+    }
+
+    /// Detaches a loop device via `LOOP_CLR_FD`.
+    pub fn loop_detach(device_path: &Path) -> Result<()> {
+        let loop_dev = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .open(device_path)
+            .with_context(|| format!("Failed to open {:?}", device_path))?;
+        unsafe { loop_clr_fd(loop_dev.as_raw_fd()) }.context("LOOP_CLR_FD ioctl failed")?;
+        Ok(())
+    }
+
+    /// Returns a block device's size in bytes via the `BLKGETSIZE64` ioctl
+    /// (the same one `blockdev --getsize64` uses).
+    pub fn block_device_size_bytes(device_path: &Path) -> Result<u64> {
+        nix::ioctl_read!(blkgetsize64, 0x12, 114, u64);
+        let dev = OpenOptions::new()
+            .read(true)
+            .open(device_path)
+            .with_context(|| format!("Failed to open {:?}", device_path))?;
+        let mut size: u64 = 0;
+        unsafe { blkgetsize64(dev.as_raw_fd(), &mut size) }.context("BLKGETSIZE64 ioctl failed")?;
+        Ok(size)
+    }
+}
+
 /// Demo thin provisioning with snapshots
-fn demonstrate_thin_provisioning() -> Result<()> {
-    println!("\n=== Thin Provisioning Demo ===\n");
+fn demonstrate_thin_provisioning(backend: Backend) -> Result<()> {
+    println!("\n=== Thin Provisioning Demo ({:?} backend) ===\n", backend);
     let pool = ThinPool::create(
         "demo-pool".to_string(),
         100,  // 100MB metadata
         1024, // 1GB data
         2048, // 1MB chunks (2048 sectors of 512 bytes each)
+        64,   // low water mark: raise a dm event with 64 data blocks (64MB) left
+        MetadataInit::Empty,
+        backend,
     )?;
+
+    let _monitor = pool.monitor(PoolThresholds {
+        data_high_water: 0.8,
+        metadata_high_water: 0.8,
+        grow_by_mb: 512,
+        poll_interval: Duration::from_secs(5),
+    });
+    println!("Started low-water-mark monitor thread for demo-pool");
+
     println!("\n--- Creating base volume ---");
     let base_volume = pool.create_thin_volume("demo-base", 500, 0)?;
 
@@ -559,15 +1910,153 @@ fn demonstrate_thin_provisioning() -> Result<()> {
     println!("\nConfirmed: Base volume unchanged despite snapshot modifications");
     println!("This demonstrates copy-on-write - the snapshot has its own copy of modified blocks");
 
+    nix::mount::umount(&mount_point)?;
+
+    println!("\n--- Encrypting the snapshot while the base volume stays plaintext ---");
+    let encryption_key = "000102030405060708090a0b0c0d0e0f101112131415161718191a1b1c1d1e";
+    let encrypted_snapshot = EncryptedVolume::create("demo-snapshot-crypt", snapshot, encryption_key)?;
+    nix::mount::mount(
+        Some(encrypted_snapshot.device_path().as_path()),
+        &mount_point,
+        Some("ext4"),
+        nix::mount::MsFlags::empty(),
+        None::<&str>,
+    )?;
+    let decrypted_content = std::fs::read_to_string(mount_point.join("shared-file.txt"))?;
+    println!(
+        "Snapshot content through the crypt mapping: {:?}",
+        decrypted_content.trim()
+    );
+    assert_eq!(decrypted_content, "Modified version in snapshot\n");
+    nix::mount::umount(&mount_point)?;
+    println!("Base volume remains plaintext; only the snapshot is encrypted at rest");
+
+    println!("\n--- Creating volume with external origin (shared read-only image) ---");
+    let base_image_backing = PathBuf::from("/tmp/demo-base-image.img");
+    let _ = std::fs::remove_file(&base_image_backing);
+    let mut base_image = LoopDevice::create(base_image_backing, 200, backend)
+        .context("Failed to create external origin loop device")?;
+    let status = Command::new("mkfs.ext4")
+        .arg("-q")
+        .arg(base_image.device_path())
+        .status()
+        .context("Failed to format external origin image")?;
+    if !status.success() {
+        anyhow::bail!("mkfs.ext4 failed for external origin image");
+    }
+    nix::mount::mount(
+        Some(base_image.device_path()),
+        &mount_point,
+        Some("ext4"),
+        nix::mount::MsFlags::empty(),
+        None::<&str>,
+    )?;
+    std::fs::write(
+        mount_point.join("image-file.txt"),
+        "This file lives on the shared read-only base image\n",
+    )?;
+    nix::mount::umount(&mount_point)?;
+
+    let overlay_volume =
+        pool.create_thin_volume_with_external_origin("demo-overlay", base_image.device_path(), 2)?;
+    nix::mount::mount(
+        Some(overlay_volume.device_path().as_path()),
+        &mount_point,
+        Some("ext4"),
+        nix::mount::MsFlags::empty(),
+        None::<&str>,
+    )?;
+    let image_content = std::fs::read_to_string(mount_point.join("image-file.txt"))?;
+    println!(
+        "Overlay volume reads through to the base image: {:?}",
+        image_content.trim()
+    );
+    assert_eq!(
+        image_content,
+        "This file lives on the shared read-only base image\n"
+    );
+    std::fs::write(
+        mount_point.join("overlay-only-file.txt"),
+        "Written copy-on-write into the pool, not the base image\n",
+    )?;
+    println!("Wrote a new file into the overlay volume without touching the base image");
     nix::mount::umount(&mount_point)?;
     std::fs::remove_dir(&mount_point)?;
+    base_image.detach()?;
 
     println!("\n--- Pool Status ---");
-    let output = Command::new("dmsetup")
-        .arg("status")
-        .arg("demo-pool")
-        .output()?;
-    println!("{}", String::from_utf8_lossy(&output.stdout));
+    let pool_status = pool.status()?;
+    println!(
+        "Pool {:?} (transaction {}): metadata {}/{} blocks, data {}/{} blocks, needs_check={}, held_metadata_root={:?}",
+        pool_status.mode,
+        pool_status.transaction_id,
+        pool_status.used_metadata_blocks,
+        pool_status.total_metadata_blocks,
+        pool_status.used_data_blocks,
+        pool_status.total_data_blocks,
+        pool_status.needs_check,
+        pool_status.held_metadata_root,
+    );
+
+    let base_status = base_volume.status()?;
+    println!(
+        "Base volume mapped {} sectors (highest: {:?})",
+        base_status.mapped_sectors, base_status.highest_mapped_sector
+    );
+
+    println!("\n--- Dumping pool metadata (online, via metadata snapshot) ---");
+    let metadata = pool.dump_metadata()?;
+    for device in &metadata.devices {
+        println!(
+            "  thin device {}: {} mapped blocks across {} mapping(s)",
+            device.device_id,
+            device.mapped_blocks,
+            device.mappings.len()
+        );
+        for mapping in &device.mappings {
+            println!(
+                "    origin {}..{} -> data {}..{}",
+                mapping.origin_begin,
+                mapping.origin_begin + mapping.length,
+                mapping.data_begin,
+                mapping.data_begin + mapping.length,
+            );
+        }
+    }
+
+    println!("\n--- Checking metadata integrity before reusing it on a new pool ---");
+    let saved_metadata_image = PathBuf::from("/tmp/demo-pool-metadata-saved.img");
+    {
+        // Hold a reserved metadata snapshot across the copy so we read a
+        // self-consistent point-in-time view instead of racing the live,
+        // possibly mid-write backing file (the same reason `dump_metadata`
+        // reserves one before running `thin_dump --metadata-snap`).
+        let _snap_guard = MetadataSnapGuard::reserve(&pool.name, pool.backend)?;
+        std::fs::copy(pool.metadata_dev.backing_file(), &saved_metadata_image)
+            .context("Failed to snapshot metadata image for reuse demo")?;
+    }
+
+    let check_report = ThinPool::check_metadata(&saved_metadata_image)?;
+    println!(
+        "thin_check on saved metadata: errors_found={}, repair_recommended={}",
+        check_report.errors_found, check_report.repair_recommended
+    );
+    assert!(!check_report.errors_found, "saved metadata should be clean");
+
+    let reused_pool = ThinPool::create(
+        "demo-pool-reused".to_string(),
+        100,
+        1024,
+        2048,
+        64,
+        MetadataInit::Reuse {
+            metadata_image: saved_metadata_image,
+            repair_if_needed: true,
+        },
+        backend,
+    )?;
+    println!("Activated a second pool on the checked, reused metadata image");
+    drop(reused_pool);
 
     println!("Thin provisioning demonstration complete\n");
     Ok(())
@@ -579,7 +2068,7 @@ fn demonstrate_loop_device() -> Result<()> {
 
     let backing_file = PathBuf::from("/tmp/loop-demo-backing.img");
     let _ = std::fs::remove_file(&backing_file);
-    let loop_dev = LoopDevice::create(backing_file.clone(), 100)?;
+    let loop_dev = LoopDevice::create(backing_file.clone(), 100, Backend::Shell)?;
 
     println!("\nLoop device created successfully!");
     println!("Backing file: {:?}", backing_file);
@@ -611,6 +2100,7 @@ fn demonstrate_loop_device() -> Result<()> {
 
 fn main() -> Result<()> {
     // demonstrate_loop_device()?;
-    demonstrate_thin_provisioning()?;
+    demonstrate_thin_provisioning(Backend::Ioctl)?;
+    demonstrate_thin_provisioning(Backend::Shell)?;
     Ok(())
 }